@@ -0,0 +1,160 @@
+use crate::error::{Context, TdistError};
+use crate::task::Command;
+use std::path::PathBuf;
+
+/// A content-addressed cache of previously successful task runs. A task is
+/// skipped entirely when a blake3 digest over its commands' shell text and
+/// declared input files matches the last stored digest and every declared
+/// output still exists on disk.
+#[derive(Clone)]
+pub struct Cache {
+    dir: Option<PathBuf>,
+}
+
+impl Cache {
+    /// Creates a cache rooted at `dir`. Caching is disabled (every task is
+    /// always run) when `dir` is `None`, i.e. when `cache_dir` is unset.
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self { dir }
+    }
+
+    /// Hashes every command's shell text, declared outputs, `ignore_failure`
+    /// and `sandbox` flags, and the contents of its declared input files,
+    /// producing a stable digest for the task.
+    pub fn digest(commands: &[Command]) -> String {
+        let mut hasher = blake3::Hasher::new();
+
+        for command in commands {
+            hasher.update(command.shell().as_bytes());
+            hasher.update(&[command.ignore_failure() as u8, command.sandbox() as u8]);
+
+            for input in command.inputs() {
+                if let Ok(contents) = std::fs::read(input) {
+                    hasher.update(&contents);
+                }
+            }
+
+            for output in command.outputs() {
+                hasher.update(output.to_string_lossy().as_bytes());
+            }
+        }
+
+        hasher.finalize().to_hex().to_string()
+    }
+
+    /// Returns `true` if `task_name` was previously run with this exact
+    /// digest and every declared output still exists.
+    pub fn is_up_to_date(&self, task_name: &str, digest: &str, outputs: &[&PathBuf]) -> bool {
+        let Some(dir) = &self.dir else {
+            return false;
+        };
+
+        let stored = std::fs::read_to_string(dir.join(Self::file_name(task_name))).ok();
+
+        stored.as_deref() == Some(digest) && outputs.iter().all(|output| output.exists())
+    }
+
+    /// Records `digest` as the last-successful digest for `task_name`.
+    pub fn store(&self, task_name: &str, digest: &str) -> Result<(), TdistError> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+
+        std::fs::create_dir_all(dir)
+            .context(format!("creating cache directory '{}'", dir.display()))?;
+
+        std::fs::write(dir.join(Self::file_name(task_name)), digest)
+            .context(format!("writing cache digest for task '{}'", task_name))
+    }
+
+    /// Hashes `task_name` into a filename-safe digest, so a name containing
+    /// `/` or `..` can neither fail to write nor escape `cache_dir`.
+    fn file_name(task_name: &str) -> String {
+        blake3::hash(task_name.as_bytes()).to_hex().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shell(command: &str) -> Command {
+        Command::Shell {
+            command: command.to_owned(),
+            ignore_failure: false,
+            parallel: false,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            sandbox: false,
+        }
+    }
+
+    #[test]
+    fn digest_changes_with_shell_text() {
+        assert_ne!(
+            Cache::digest(&[shell("echo a")]),
+            Cache::digest(&[shell("echo b")])
+        );
+    }
+
+    #[test]
+    fn digest_is_stable_for_identical_commands() {
+        assert_eq!(
+            Cache::digest(&[shell("echo a")]),
+            Cache::digest(&[shell("echo a")])
+        );
+    }
+
+    #[test]
+    fn digest_changes_with_ignore_failure() {
+        let plain = shell("echo a");
+        let ignoring = Command::Shell {
+            command: String::from("echo a"),
+            ignore_failure: true,
+            parallel: false,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            sandbox: false,
+        };
+
+        assert_ne!(Cache::digest(&[plain]), Cache::digest(&[ignoring]));
+    }
+
+    #[test]
+    fn digest_changes_with_sandbox_flag() {
+        let plain = shell("echo a");
+        let sandboxed = Command::Shell {
+            command: String::from("echo a"),
+            ignore_failure: false,
+            parallel: false,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            sandbox: true,
+        };
+
+        assert_ne!(Cache::digest(&[plain]), Cache::digest(&[sandboxed]));
+    }
+
+    #[test]
+    fn digest_changes_with_outputs() {
+        let plain = shell("echo a");
+        let with_output = Command::Shell {
+            command: String::from("echo a"),
+            ignore_failure: false,
+            parallel: false,
+            inputs: Vec::new(),
+            outputs: vec![PathBuf::from("out.txt")],
+            sandbox: false,
+        };
+
+        assert_ne!(Cache::digest(&[plain]), Cache::digest(&[with_output]));
+    }
+
+    #[test]
+    fn file_name_sanitizes_path_like_task_names() {
+        let name = Cache::file_name("../../etc/passwd");
+
+        assert!(!name.contains('/'));
+        assert!(!name.contains(".."));
+    }
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+
+/// The crate's error type. Every fallible operation in `tdist` returns this
+/// instead of `Box<dyn std::error::Error>`, so failures keep enough
+/// structure to later be collected and serialized into a run report.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum TdistError {
+    /// The config file could not be loaded.
+    ConfigLoad { message: String },
+    /// A task file failed to parse.
+    TaskFileParse { path: PathBuf, message: String },
+    /// A `[[command]]` table in a task file was invalid.
+    InvalidCommand { message: String },
+    /// A command ran but did not succeed (a non-zero exit status, or killed
+    /// by a signal).
+    CommandExecution {
+        task_id: i32,
+        command: String,
+        message: String,
+    },
+    /// Any other error, optionally carrying a chain of added context.
+    Other { message: String },
+}
+
+impl TdistError {
+    pub fn other(message: impl Into<String>) -> Self {
+        Self::Other {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for TdistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ConfigLoad { message } => write!(f, "loading config: {}", message),
+            Self::TaskFileParse { path, message } => {
+                write!(f, "parsing task file '{}': {}", path.display(), message)
+            }
+            Self::InvalidCommand { message } => write!(f, "invalid command: {}", message),
+            Self::CommandExecution {
+                task_id,
+                command,
+                message,
+            } => write!(
+                f,
+                "task {} command `{}` failed: {}",
+                task_id, command, message
+            ),
+            Self::Other { message } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for TdistError {}
+
+impl From<std::io::Error> for TdistError {
+    fn from(err: std::io::Error) -> Self {
+        Self::other(err.to_string())
+    }
+}
+
+impl From<confy::ConfyError> for TdistError {
+    fn from(err: confy::ConfyError) -> Self {
+        Self::ConfigLoad {
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<std::sync::mpsc::RecvError> for TdistError {
+    fn from(err: std::sync::mpsc::RecvError) -> Self {
+        Self::other(err.to_string())
+    }
+}
+
+/// Adds human-readable context to a `Result`'s error, mirroring
+/// `anyhow::Context` while keeping the underlying error serializable: the
+/// original error and the added context are folded into `TdistError::Other`.
+pub trait Context<T> {
+    fn context(self, message: impl Into<String>) -> Result<T, TdistError>;
+}
+
+impl<T, E: fmt::Display> Context<T> for Result<T, E> {
+    fn context(self, message: impl Into<String>) -> Result<T, TdistError> {
+        self.map_err(|err| TdistError::Other {
+            message: format!("{}: {}", message.into(), err),
+        })
+    }
+}
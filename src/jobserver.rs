@@ -0,0 +1,59 @@
+use std::sync::{Arc, Condvar, Mutex};
+
+/// A GNU-make-style jobserver: a bounded pool of tokens that gates how many
+/// parallel shell commands may run at once across every task, instead of
+/// letting each task spawn as many threads as it likes.
+///
+/// The worker thread that owns a running `Task` implicitly counts as
+/// forward progress on its own, so only commands spawned for `parallel`
+/// execution need to acquire a token.
+#[derive(Clone)]
+pub struct JobServer {
+    inner: Arc<(Mutex<usize>, Condvar)>,
+}
+
+impl JobServer {
+    /// Creates a jobserver with `tokens` tokens available up front.
+    pub fn new(tokens: usize) -> Self {
+        Self {
+            inner: Arc::new((Mutex::new(tokens), Condvar::new())),
+        }
+    }
+
+    /// Blocks until a token is available, then returns a guard that holds
+    /// it until dropped.
+    pub fn acquire(&self) -> JobToken {
+        let (lock, condvar) = &*self.inner;
+        let mut available = lock.lock().unwrap();
+
+        while *available == 0 {
+            available = condvar.wait(available).unwrap();
+        }
+
+        *available -= 1;
+
+        JobToken {
+            server: self.clone(),
+        }
+    }
+
+    fn release(&self) {
+        let (lock, condvar) = &*self.inner;
+        let mut available = lock.lock().unwrap();
+
+        *available += 1;
+
+        condvar.notify_one();
+    }
+}
+
+/// A held jobserver token. Returns the token to the pool on drop.
+pub struct JobToken {
+    server: JobServer,
+}
+
+impl Drop for JobToken {
+    fn drop(&mut self) {
+        self.server.release();
+    }
+}
@@ -1,3 +1,8 @@
+use crate::cache::Cache;
+use crate::error::TdistError;
+use crate::jobserver::JobServer;
+use crate::sandbox::SandboxConfig;
+use crate::schedule::{BuildError, Graph, TaskResult};
 use crate::task::Task;
 use crate::task_file::{get_task_files, TaskFile};
 use clap::{Parser, Subcommand};
@@ -5,13 +10,20 @@ use crossbeam::deque::{Steal, Stealer, Worker};
 use serde::{Deserialize, Serialize};
 use std::{
     path::{Path, PathBuf},
-    sync::atomic::{AtomicI32, Ordering},
+    sync::atomic::AtomicI32,
+    sync::mpsc::Sender,
     thread::JoinHandle,
 };
 use tracing::{error, info, warn};
 
+mod cache;
+mod error;
+mod jobserver;
+mod sandbox;
+mod schedule;
 mod task;
 mod task_file;
+mod template;
 
 #[derive(Parser)]
 struct Args {
@@ -36,6 +48,32 @@ struct Config {
     threads: usize,
     log_level: String,
     taskfile_dir: PathBuf,
+    /// Maximum number of parallel commands allowed to run at once across
+    /// every task. Defaults to `threads` when unset.
+    #[serde(default)]
+    max_jobs: Option<usize>,
+    /// Directory used to store content-addressed digests of successful
+    /// task runs. Caching is disabled when unset.
+    #[serde(default)]
+    cache_dir: Option<PathBuf>,
+    /// Glob patterns, relative to `taskfile_dir`, of files to load as task
+    /// files.
+    #[serde(default = "default_include")]
+    include: Vec<String>,
+    /// Glob patterns, relative to `taskfile_dir`, to exclude from `include`.
+    #[serde(default)]
+    exclude: Vec<String>,
+    /// Variables available to every task file's command templates.
+    #[serde(default)]
+    variables: std::collections::HashMap<String, String>,
+    /// Controls the opt-in namespace sandbox used for commands with
+    /// `sandbox = true`.
+    #[serde(default)]
+    sandbox: SandboxConfig,
+}
+
+fn default_include() -> Vec<String> {
+    vec![String::from("**/*.toml")]
 }
 
 impl Default for Config {
@@ -44,11 +82,17 @@ impl Default for Config {
             threads: 4,
             log_level: String::from("info"),
             taskfile_dir: PathBuf::from("taskfiles"),
+            max_jobs: None,
+            cache_dir: None,
+            include: default_include(),
+            variables: std::collections::HashMap::new(),
+            exclude: Vec::new(),
+            sandbox: SandboxConfig::default(),
         }
     }
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> Result<(), TdistError> {
     let args = Args::parse();
 
     match args.command {
@@ -57,7 +101,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 }
 
-fn edit_config(config: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+fn edit_config(config: Option<PathBuf>) -> Result<(), TdistError> {
     let editor = std::env::var("EDITOR").unwrap_or_else(|_| String::from("vi"));
 
     let config_path = match config {
@@ -74,23 +118,23 @@ fn edit_config(config: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>
     if status.success() {
         Ok(())
     } else {
-        Err(format!("Editor failed: {}", status).into())
+        Err(TdistError::other(format!("Editor failed: {}", status)))
     }
 }
 
-fn main_run(config: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+fn main_run(config: Option<PathBuf>) -> Result<(), TdistError> {
     let config: Config = match config {
         Some(path) => confy::load_path(path),
         None => confy::load("tdist", Some("config")),
     }
-    .map_err(|err| {
-        format!(
-            "Loading config from '{}': {}",
+    .map_err(|err| TdistError::ConfigLoad {
+        message: format!(
+            "loading config from '{}': {}",
             confy::get_configuration_file_path("tdist", Some("config"))
-                .unwrap()
-                .to_string_lossy(),
+                .map(|path| path.to_string_lossy().into_owned())
+                .unwrap_or_default(),
             err
-        )
+        ),
     })?;
 
     let max_level = match config.log_level.as_str().to_lowercase().as_str() {
@@ -99,7 +143,12 @@ fn main_run(config: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
         "info" => tracing::Level::INFO,
         "warn" => tracing::Level::WARN,
         "error" => tracing::Level::ERROR,
-        _ => return Err(format!("Invalid log level: {}", config.log_level).into()),
+        _ => {
+            return Err(TdistError::other(format!(
+                "Invalid log level: {}",
+                config.log_level
+            )))
+        }
     };
 
     tracing_subscriber::fmt()
@@ -118,15 +167,28 @@ fn main_run(config: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
     })
 }
 
-fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+fn run(config: Config) -> Result<(), TdistError> {
     let worker = Worker::<Task>::new_fifo();
 
     let stealer = worker.stealer();
 
+    let (result_tx, result_rx) = std::sync::mpsc::channel::<TaskResult>();
+
+    let jobserver = JobServer::new(config.max_jobs.unwrap_or(config.threads));
+    let cache = Cache::new(config.cache_dir.clone());
+    let sandbox_config = config.sandbox.clone();
+
     for id in 0..config.threads {
         let thread_name = format!("worker-{}", id);
 
-        match start_worker_thread(thread_name.clone(), stealer.clone()) {
+        match start_worker_thread(
+            thread_name.clone(),
+            stealer.clone(),
+            result_tx.clone(),
+            jobserver.clone(),
+            cache.clone(),
+            sandbox_config.clone(),
+        ) {
             Ok(_) => info!("Started {}", thread_name),
             Err(error) => error!("Starting {}: {}", thread_name, error),
         }
@@ -139,40 +201,101 @@ fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
         task_dir.to_string_lossy()
     );
 
-    let files = get_task_files(task_dir)?;
+    let files = get_task_files(task_dir, &config.include, &config.exclude)?;
 
     let next_task_id = AtomicI32::new(0);
+    let mut tasks = Vec::new();
 
     for file in files {
         let file_content = std::fs::read_to_string(&file)?;
-        let task_file: TaskFile = toml::from_str(&file_content)?;
+        let task_file: TaskFile =
+            toml::from_str(&file_content).map_err(|err| TdistError::TaskFileParse {
+                path: file.clone(),
+                message: err.to_string(),
+            })?;
 
-        let task = Task::from_task_file(task_file, &next_task_id);
+        let task = Task::from_task_file(task_file, &next_task_id, &config.variables)?;
 
         info!("Creating task {}: {}", task.id, task.name);
 
-        worker.push(task);
+        tasks.push(task);
     }
 
-    if next_task_id.load(Ordering::SeqCst) == 0 {
+    if tasks.is_empty() {
         warn!("No tasks found");
+
+        loop {
+            std::thread::sleep(std::time::Duration::from_secs(1));
+        }
     }
 
-    loop {
-        std::thread::sleep(std::time::Duration::from_secs(1));
+    let mut graph = Graph::build(tasks).map_err(|err| match err {
+        BuildError::DuplicateName(name) => {
+            TdistError::other(format!("Two or more task files declare the name '{}'", name))
+        }
+        BuildError::UnknownDependency { task, dependency } => TdistError::other(format!(
+            "Task '{}' depends on unknown task '{}'",
+            task, dependency
+        )),
+        BuildError::Cycle(cyclic) => TdistError::other(format!(
+            "Dependency cycle detected; these tasks never became runnable: {}",
+            cyclic.join(", ")
+        )),
+    })?;
+
+    for task in graph.take_ready() {
+        info!("Scheduling task {}: {}", task.id, task.name);
+
+        worker.push(task);
+    }
+
+    while !graph.is_done() {
+        let TaskResult { name, success } = result_rx.recv()?;
+
+        if success {
+            graph.complete(&name);
+
+            for task in graph.take_ready() {
+                info!("Scheduling task {}: {}", task.id, task.name);
+
+                worker.push(task);
+            }
+        } else {
+            let skipped = graph.fail(&name);
+
+            if !skipped.is_empty() {
+                warn!(
+                    "Skipping tasks that depend on failed task '{}': {}",
+                    name,
+                    skipped.join(", ")
+                );
+            }
+        }
     }
+
+    Ok(())
 }
 
 fn start_worker_thread(
     thread_name: String,
     stealer: Stealer<Task>,
+    results: Sender<TaskResult>,
+    jobserver: JobServer,
+    cache: Cache,
+    sandbox_config: SandboxConfig,
 ) -> std::io::Result<JoinHandle<()>> {
     std::thread::Builder::new()
         .name(thread_name.clone())
-        .spawn(|| task_stealer(stealer))
+        .spawn(|| task_stealer(stealer, results, jobserver, cache, sandbox_config))
 }
 
-fn task_stealer(stealer: Stealer<Task>) {
+fn task_stealer(
+    stealer: Stealer<Task>,
+    results: Sender<TaskResult>,
+    jobserver: JobServer,
+    cache: Cache,
+    sandbox_config: SandboxConfig,
+) {
     let backoff = crossbeam::utils::Backoff::new();
     let mut should_print_warning = true;
 
@@ -191,12 +314,25 @@ fn task_stealer(stealer: Stealer<Task>) {
             Steal::Success(task) => {
                 info!("Running task {}: {}", task.id, task.name);
 
-                if let Err(error) = task.run() {
-                    error!("{}", error);
-                }
+                let success = match task.run(&jobserver, &cache, &sandbox_config) {
+                    Ok(()) => true,
+                    Err(error) => {
+                        error!("{}", error);
+
+                        false
+                    }
+                };
 
                 info!("Finished task {}", task.id);
 
+                let name = task.name.clone();
+
+                if results.send(TaskResult { name, success }).is_err() {
+                    warn!("Scheduler is gone, stopping");
+
+                    return;
+                }
+
                 backoff.reset();
                 should_print_warning = true;
             }
@@ -0,0 +1,453 @@
+use crate::error::TdistError;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Configuration for the optional namespace sandbox. A command only runs
+/// inside a sandbox when both `enabled` here and the command's own
+/// `sandbox = true` flag are set; otherwise it runs directly via `sh -c`,
+/// same as before. Sandboxing is only implemented on Linux.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SandboxConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Directory the sandboxed command is `chroot`ed into. When
+    /// `readonly_root` is unset this is bind-mounted over itself and used
+    /// as-is (read-write); otherwise the command's declared `inputs` are
+    /// mounted into it on top of whatever it already contains.
+    #[serde(default = "default_root")]
+    pub root: PathBuf,
+    /// When set, each command's declared `inputs` are bind-mounted
+    /// read-only at the same absolute path under `root`, instead of `root`
+    /// itself being bind-mounted read-write. Nothing is removed from `root`
+    /// first, so whatever it already holds stays visible as well (with the
+    /// default root of `/`, the whole host filesystem).
+    #[serde(default)]
+    pub readonly_root: bool,
+}
+
+fn default_root() -> PathBuf {
+    PathBuf::from("/")
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            root: default_root(),
+            readonly_root: false,
+        }
+    }
+}
+
+/// Runs `command` via `sh -c`, isolated in new user/mount/PID namespaces
+/// when sandboxing is enabled and requested on Linux; falls back to direct
+/// execution everywhere else.
+pub fn run(
+    command: &str,
+    sandboxed: bool,
+    config: &SandboxConfig,
+    inputs: &[PathBuf],
+) -> Result<std::process::ExitStatus, TdistError> {
+    #[cfg(target_os = "linux")]
+    if sandboxed && config.enabled {
+        return linux::run(command, config, inputs);
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    let (_, _) = (sandboxed, config);
+
+    let _ = inputs;
+
+    std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .status()
+        .map_err(TdistError::from)
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::SandboxConfig;
+    use crate::error::{Context, TdistError};
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::process::ExitStatusExt;
+    use std::path::{Path, PathBuf};
+
+    /// Everything the forked child needs, rendered to owned C strings
+    /// *before* forking. Between `fork` and `execv` the child must not
+    /// allocate (see [`run`]), so no formatting or `CString` construction
+    /// may happen past that point — it all happens here instead.
+    struct Prepared {
+        uid_map: CString,
+        gid_map: CString,
+        mounts: Vec<Mount>,
+        root: CString,
+        shell_path: CString,
+        dash_c: CString,
+        command: CString,
+    }
+
+    struct Mount {
+        source: CString,
+        target: CString,
+        readonly: bool,
+    }
+
+    /// Runs `command` in a new user/mount/PID namespace.
+    ///
+    /// `unshare(CLONE_NEWUSER)` returns `EINVAL` when called from a
+    /// multithreaded process, which `tdist` always is (one thread per
+    /// worker), and a namespace entered via `unshare(CLONE_NEWPID)` only
+    /// applies to children created *after* the call, never to the caller
+    /// itself. So the work happens across two forks: `setup` is a
+    /// freshly forked, single-threaded process that can `unshare` without
+    /// `EINVAL`, and it forks again so the grandchild is actually born
+    /// inside the new PID namespace before it execs the shell. The
+    /// original worker thread's namespaces are never touched.
+    ///
+    /// `fork` only duplicates the calling *thread*; any lock another
+    /// thread held at that instant (e.g. the allocator's) is copied into
+    /// the child still "held", with no thread left to release it. So
+    /// everything the child needs is pre-rendered into `Prepared` here,
+    /// and the forked paths below (`setup`, `exec_shell`, `die`) touch
+    /// only raw syscalls and that pre-rendered data until they exec or
+    /// `_exit`.
+    pub fn run(
+        command: &str,
+        config: &SandboxConfig,
+        inputs: &[PathBuf],
+    ) -> Result<std::process::ExitStatus, TdistError> {
+        let prepared = prepare(command, config, inputs)?;
+
+        // Safety: `fork` duplicates the calling process. The child path
+        // (`setup`) never returns to this function; it always exits the
+        // process, so control only returns here in the parent.
+        let pid = unsafe { libc::fork() };
+
+        if pid < 0 {
+            return Err(std::io::Error::last_os_error()).context("forking sandbox setup process");
+        }
+
+        if pid == 0 {
+            setup(&prepared);
+        }
+
+        waitpid_raw(pid).map_err(|()| TdistError::other("waiting for sandbox setup process"))
+    }
+
+    fn prepare(
+        command: &str,
+        config: &SandboxConfig,
+        inputs: &[PathBuf],
+    ) -> Result<Prepared, TdistError> {
+        // Safety: `getuid`/`getgid` take no arguments and cannot fail.
+        let uid = unsafe { libc::getuid() };
+        let gid = unsafe { libc::getgid() };
+
+        let to_cstring = |s: String| CString::new(s).map_err(|err| TdistError::other(err.to_string()));
+
+        std::fs::create_dir_all(&config.root)
+            .context(format!("creating sandbox root '{}'", config.root.display()))?;
+
+        let mut mounts = if config.readonly_root {
+            prepare_input_mounts(&config.root, inputs)?
+        } else {
+            vec![prepare_mount(&config.root, &config.root, false)?]
+        };
+
+        let dev_target = config.root.join("dev");
+
+        std::fs::create_dir_all(&dev_target)
+            .context(format!("creating sandbox '{}'", dev_target.display()))?;
+
+        mounts.push(prepare_mount(Path::new("/dev"), &dev_target, false)?);
+
+        Ok(Prepared {
+            uid_map: to_cstring(format!("0 {} 1", uid))?,
+            gid_map: to_cstring(format!("0 {} 1", gid))?,
+            mounts,
+            root: CString::new(config.root.as_os_str().as_bytes())
+                .map_err(|err| TdistError::other(err.to_string()))?,
+            shell_path: CString::new("/bin/sh").expect("no interior nul"),
+            dash_c: CString::new("-c").expect("no interior nul"),
+            command: CString::new(command).map_err(|err| TdistError::other(err.to_string()))?,
+        })
+    }
+
+    /// Bind-mounts every declared `input` read-only at the same absolute
+    /// path under `root`, creating just enough scaffolding directories (and
+    /// an empty mount-point file, unless one already exists) to hold each
+    /// mount point. Paths already present in `root` stay visible alongside
+    /// the inputs; undeclared paths are hidden only when `root` starts out
+    /// without them.
+    fn prepare_input_mounts(root: &Path, inputs: &[PathBuf]) -> Result<Vec<Mount>, TdistError> {
+        inputs
+            .iter()
+            .map(|input| {
+                let relative = input.strip_prefix("/").unwrap_or(input);
+                let target = root.join(relative);
+
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent)
+                        .context(format!("creating sandbox mount point '{}'", parent.display()))?;
+                }
+
+                if input.is_dir() {
+                    std::fs::create_dir_all(&target)
+                } else {
+                    std::fs::OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .open(&target)
+                        .map(|_| ())
+                }
+                .context(format!("creating sandbox mount point '{}'", target.display()))?;
+
+                prepare_mount(input, &target, true)
+            })
+            .collect()
+    }
+
+    fn prepare_mount(source: &Path, target: &Path, readonly: bool) -> Result<Mount, TdistError> {
+        Ok(Mount {
+            source: CString::new(source.as_os_str().as_bytes())
+                .map_err(|err| TdistError::other(err.to_string()))?,
+            target: CString::new(target.as_os_str().as_bytes())
+                .map_err(|err| TdistError::other(err.to_string()))?,
+            readonly,
+        })
+    }
+
+    /// Runs in the forked setup process: enters the sandbox namespaces,
+    /// forks the command into the new PID namespace, waits for it, then
+    /// exits with its status. Never returns. Must not allocate (see
+    /// [`run`]).
+    fn setup(prepared: &Prepared) -> ! {
+        if enter_namespaces(prepared).is_err() {
+            die(b"tdist: entering sandbox namespaces failed\n");
+        }
+
+        // Safety: same as the outer `fork` in `run`.
+        let pid = unsafe { libc::fork() };
+
+        if pid < 0 {
+            die(b"tdist: forking sandboxed command failed\n");
+        }
+
+        if pid == 0 {
+            exec_shell(prepared);
+        }
+
+        match waitpid_raw(pid) {
+            // Safety: `_exit` terminates immediately, skipping the
+            // allocating atexit/cleanup work `std::process::exit` does —
+            // required here since this process may have inherited a
+            // locked allocator from another thread in the parent.
+            Ok(status) => unsafe {
+                libc::_exit(
+                    status
+                        .code()
+                        .unwrap_or_else(|| status.signal().map(|signal| 128 + signal).unwrap_or(127)),
+                )
+            },
+            Err(()) => die(b"tdist: waiting for sandboxed command failed\n"),
+        }
+    }
+
+    /// Execs `sh -c command`, replacing the calling (PID-namespace-local)
+    /// process image. Never returns. Must not allocate (see [`run`]).
+    fn exec_shell(prepared: &Prepared) -> ! {
+        let argv = [
+            prepared.shell_path.as_ptr(),
+            prepared.dash_c.as_ptr(),
+            prepared.command.as_ptr(),
+            std::ptr::null(),
+        ];
+
+        // Safety: `argv` is nul-terminated and every element stays valid
+        // for the duration of this call; `execv` only returns on failure.
+        unsafe {
+            libc::execv(prepared.shell_path.as_ptr(), argv.as_ptr());
+        }
+
+        die(b"tdist: exec'ing sandboxed shell failed\n");
+    }
+
+    /// Writes a static diagnostic to stderr and terminates without
+    /// allocating. Never returns.
+    fn die(message: &[u8]) -> ! {
+        // Safety: `message` is a valid byte slice for its own length; a
+        // short write here is a best-effort diagnostic right before exit.
+        unsafe {
+            libc::write(2, message.as_ptr().cast(), message.len());
+        }
+
+        // Safety: see the note on `_exit` in `setup`.
+        unsafe { libc::_exit(127) };
+    }
+
+    /// Waits for `pid`, retrying on `EINTR` like the `std::process::Command`
+    /// path this replaced. Returns `Err(())` instead of a `TdistError` so it
+    /// can be called from the non-allocating forked paths too.
+    fn waitpid_raw(pid: libc::pid_t) -> Result<std::process::ExitStatus, ()> {
+        let mut raw_status = 0;
+
+        loop {
+            // Safety: `pid` was returned by a `fork` this same process
+            // performed and hasn't been waited on yet.
+            if unsafe { libc::waitpid(pid, &mut raw_status, 0) } >= 0 {
+                return Ok(std::process::ExitStatus::from_raw(raw_status));
+            }
+
+            if std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted {
+                return Err(());
+            }
+        }
+    }
+
+    fn enter_namespaces(prepared: &Prepared) -> Result<(), ()> {
+        let flags = libc::CLONE_NEWUSER | libc::CLONE_NEWNS | libc::CLONE_NEWPID;
+
+        // Safety: only affects the calling (freshly forked, single
+        // threaded) process's namespaces; no other resources are held
+        // across this call.
+        if unsafe { libc::unshare(flags) } != 0 {
+            return Err(());
+        }
+
+        map_current_user(prepared)?;
+        make_mounts_private()?;
+        mount_root(prepared)?;
+        enter_root(prepared)?;
+
+        Ok(())
+    }
+
+    /// `chroot`s into `config.root` and `chdir`s to `/` so the sandboxed
+    /// command actually runs rooted there instead of at the host's `/` —
+    /// bind-mounting `root` alone, without this, leaves the mount
+    /// namespace pointed at the unchanged host filesystem.
+    fn enter_root(prepared: &Prepared) -> Result<(), ()> {
+        // Safety: `prepared.root` is a valid nul-terminated C string built
+        // in `prepare`, before any of the mounts it names existed.
+        if unsafe { libc::chroot(prepared.root.as_ptr()) } != 0 {
+            return Err(());
+        }
+
+        // Safety: `b"/\0"` is a valid nul-terminated C string; after
+        // `chroot` it refers to the new root.
+        if unsafe { libc::chdir(b"/\0".as_ptr().cast()) } != 0 {
+            return Err(());
+        }
+
+        Ok(())
+    }
+
+    fn map_current_user(prepared: &Prepared) -> Result<(), ()> {
+        write_file(b"/proc/self/setgroups\0", b"deny")?;
+        write_file(b"/proc/self/uid_map\0", prepared.uid_map.as_bytes())?;
+        write_file(b"/proc/self/gid_map\0", prepared.gid_map.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Marks the whole mount tree `MS_PRIVATE` so sandbox bind mounts never
+    /// propagate back out to the host's mount namespace.
+    fn make_mounts_private() -> Result<(), ()> {
+        // Safety: `b"/\0"` is a valid nul-terminated C string; a `None`
+        // source/fstype/data is valid for a propagation-only remount.
+        let result = unsafe {
+            libc::mount(
+                std::ptr::null(),
+                b"/\0".as_ptr().cast(),
+                std::ptr::null(),
+                (libc::MS_REC | libc::MS_PRIVATE) as libc::c_ulong,
+                std::ptr::null(),
+            )
+        };
+
+        if result != 0 {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn mount_root(prepared: &Prepared) -> Result<(), ()> {
+        for mount in &prepared.mounts {
+            bind_mount(mount)?;
+        }
+
+        Ok(())
+    }
+
+    fn bind_mount(mount: &Mount) -> Result<(), ()> {
+        // Safety: both paths are valid, nul-terminated C strings rendered
+        // ahead of time in `prepare`; no other flags dereference extra
+        // pointers.
+        let result = unsafe {
+            libc::mount(
+                mount.source.as_ptr(),
+                mount.target.as_ptr(),
+                std::ptr::null(),
+                libc::MS_BIND as libc::c_ulong,
+                std::ptr::null(),
+            )
+        };
+
+        if result != 0 {
+            return Err(());
+        }
+
+        if !mount.readonly {
+            return Ok(());
+        }
+
+        // A bind mount's own flags are ignored by the initial `mount(2)`
+        // call; making it read-only needs a second remount pass.
+        //
+        // Safety: same as above.
+        let result = unsafe {
+            libc::mount(
+                mount.source.as_ptr(),
+                mount.target.as_ptr(),
+                std::ptr::null(),
+                (libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY) as libc::c_ulong,
+                std::ptr::null(),
+            )
+        };
+
+        if result != 0 {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Writes `data` to the file at nul-terminated `path` via raw syscalls,
+    /// with no allocation — safe to call from the forked child.
+    fn write_file(path: &[u8], data: &[u8]) -> Result<(), ()> {
+        // Safety: `path` is a valid nul-terminated C string.
+        let fd = unsafe { libc::open(path.as_ptr().cast(), libc::O_WRONLY) };
+
+        if fd < 0 {
+            return Err(());
+        }
+
+        // Safety: `fd` was just opened above; `data` is a valid byte slice
+        // for its own length.
+        let written = unsafe { libc::write(fd, data.as_ptr().cast(), data.len()) };
+
+        // Safety: `fd` was just opened above and is closed exactly once.
+        unsafe {
+            libc::close(fd);
+        }
+
+        if written < 0 || written as usize != data.len() {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use crate::task::Task;
+use std::collections::HashMap;
+
+/// The outcome of running a single task, reported back to the scheduler by
+/// whichever worker thread ran it.
+pub struct TaskResult {
+    pub name: String,
+    pub success: bool,
+}
+
+/// Why `Graph::build` refused to build a graph.
+pub enum BuildError {
+    /// Two or more task files declared the same `name`; since the graph is
+    /// keyed by name, building would silently drop all but one of them.
+    DuplicateName(String),
+    /// A task's `depends_on` names a task that doesn't exist, most likely a
+    /// typo.
+    UnknownDependency { task: String, dependency: String },
+    /// A `depends_on` cycle left these tasks permanently unreachable.
+    Cycle(Vec<String>),
+}
+
+/// A dependency graph over a fixed set of tasks, keyed by task name, driving
+/// Kahn's-algorithm style topological scheduling: tasks become eligible to
+/// run once every task they depend on has finished successfully.
+pub struct Graph {
+    /// Tasks not yet handed to the worker pool, keyed by name.
+    pending: HashMap<String, Task>,
+    /// Remaining unsatisfied dependency count for each pending task.
+    in_degree: HashMap<String, usize>,
+    /// Tasks that depend on a given task, keyed by the dependency's name.
+    dependents: HashMap<String, Vec<String>>,
+    /// Tasks neither completed nor failed (and not yet counted as such).
+    remaining: usize,
+}
+
+impl Graph {
+    /// Builds the graph from a flat list of tasks, failing if two tasks
+    /// share a `name` (the graph is keyed by name, so a duplicate would
+    /// silently vanish), if a `depends_on` names a task that doesn't exist,
+    /// or if any `depends_on` cycle makes some tasks unreachable by Kahn's
+    /// algorithm.
+    pub fn build(tasks: Vec<Task>) -> Result<Self, BuildError> {
+        let mut seen = std::collections::HashSet::new();
+
+        for task in &tasks {
+            if !seen.insert(task.name.clone()) {
+                return Err(BuildError::DuplicateName(task.name.clone()));
+            }
+        }
+
+        for task in &tasks {
+            for dependency in &task.depends_on {
+                if !seen.contains(dependency) {
+                    return Err(BuildError::UnknownDependency {
+                        task: task.name.clone(),
+                        dependency: dependency.clone(),
+                    });
+                }
+            }
+        }
+
+        let mut in_degree = HashMap::new();
+        let mut dependents: HashMap<String, Vec<String>> = HashMap::new();
+
+        for task in &tasks {
+            in_degree.insert(task.name.clone(), task.depends_on.len());
+
+            for dependency in &task.depends_on {
+                dependents
+                    .entry(dependency.clone())
+                    .or_default()
+                    .push(task.name.clone());
+            }
+        }
+
+        let remaining = tasks.len();
+        let pending = tasks
+            .into_iter()
+            .map(|task| (task.name.clone(), task))
+            .collect::<HashMap<_, _>>();
+
+        let graph = Self {
+            pending,
+            in_degree,
+            dependents,
+            remaining,
+        };
+
+        graph.detect_cycles()?;
+
+        Ok(graph)
+    }
+
+    /// Runs Kahn's algorithm against a scratch copy of the in-degree table
+    /// and reports the names of any tasks that never reach in-degree zero.
+    fn detect_cycles(&self) -> Result<(), BuildError> {
+        let mut in_degree = self.in_degree.clone();
+        let mut queue: Vec<String> = in_degree
+            .iter()
+            .filter(|(_, degree)| **degree == 0)
+            .map(|(name, _)| name.clone())
+            .collect();
+        let mut visited = 0;
+
+        while let Some(name) = queue.pop() {
+            visited += 1;
+
+            if let Some(dependents) = self.dependents.get(&name) {
+                for dependent in dependents {
+                    if let Some(degree) = in_degree.get_mut(dependent) {
+                        *degree -= 1;
+
+                        if *degree == 0 {
+                            queue.push(dependent.clone());
+                        }
+                    }
+                }
+            }
+        }
+
+        if visited == self.pending.len() {
+            Ok(())
+        } else {
+            Err(BuildError::Cycle(
+                in_degree
+                    .into_iter()
+                    .filter(|(_, degree)| *degree > 0)
+                    .map(|(name, _)| name)
+                    .collect(),
+            ))
+        }
+    }
+
+    /// Removes and returns every task that is currently ready to run, i.e.
+    /// every pending task whose dependencies have all completed.
+    pub fn take_ready(&mut self) -> Vec<Task> {
+        let ready_names: Vec<String> = self
+            .in_degree
+            .iter()
+            .filter(|(_, degree)| **degree == 0)
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        ready_names
+            .into_iter()
+            .filter_map(|name| {
+                self.in_degree.remove(&name);
+                self.pending.remove(&name)
+            })
+            .collect()
+    }
+
+    /// Records that `name` finished successfully, unblocking any dependents
+    /// whose other dependencies are already satisfied.
+    pub fn complete(&mut self, name: &str) {
+        self.remaining = self.remaining.saturating_sub(1);
+
+        if let Some(dependents) = self.dependents.get(name) {
+            for dependent in dependents {
+                if let Some(degree) = self.in_degree.get_mut(dependent) {
+                    *degree = degree.saturating_sub(1);
+                }
+            }
+        }
+    }
+
+    /// Records that `name` failed, transitively failing every pending task
+    /// that (directly or indirectly) depends on it, and returns their names.
+    pub fn fail(&mut self, name: &str) -> Vec<String> {
+        self.remaining = self.remaining.saturating_sub(1);
+
+        let mut failed = Vec::new();
+        let mut stack = vec![name.to_owned()];
+
+        while let Some(current) = stack.pop() {
+            let Some(dependents) = self.dependents.get(&current) else {
+                continue;
+            };
+
+            for dependent in dependents.clone() {
+                if self.pending.remove(&dependent).is_some() {
+                    self.in_degree.remove(&dependent);
+                    self.remaining = self.remaining.saturating_sub(1);
+                    failed.push(dependent.clone());
+                    stack.push(dependent);
+                }
+            }
+        }
+
+        failed
+    }
+
+    /// Whether every task has either completed or been failed.
+    pub fn is_done(&self) -> bool {
+        self.remaining == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicI32;
+
+    fn task(name: &str, depends_on: &[&str]) -> Task {
+        let task_file = crate::task_file::TaskFile {
+            name: name.to_owned(),
+            repeat: crate::task_file::Repeat(1),
+            depends_on: depends_on.iter().map(|name| name.to_string()).collect(),
+            vars: HashMap::new(),
+            commands: Vec::new(),
+        };
+
+        Task::from_task_file(task_file, &AtomicI32::new(0), &HashMap::new()).unwrap()
+    }
+
+    #[test]
+    fn build_orders_independent_tasks_as_immediately_ready() {
+        let mut graph = Graph::build(vec![task("a", &[]), task("b", &[])]).ok().unwrap();
+
+        let mut ready: Vec<String> = graph.take_ready().into_iter().map(|t| t.name).collect();
+        ready.sort();
+
+        assert_eq!(ready, vec!["a", "b"]);
+        assert!(!graph.is_done());
+    }
+
+    #[test]
+    fn take_ready_unblocks_dependents_only_once_dependency_completes() {
+        let mut graph = Graph::build(vec![task("a", &[]), task("b", &["a"])])
+            .ok()
+            .unwrap();
+
+        let ready: Vec<String> = graph.take_ready().into_iter().map(|t| t.name).collect();
+        assert_eq!(ready, vec!["a"]);
+
+        graph.complete("a");
+
+        let ready: Vec<String> = graph.take_ready().into_iter().map(|t| t.name).collect();
+        assert_eq!(ready, vec!["b"]);
+
+        graph.complete("b");
+        assert!(graph.is_done());
+    }
+
+    #[test]
+    fn fail_transitively_skips_dependents() {
+        let mut graph = Graph::build(vec![
+            task("a", &[]),
+            task("b", &["a"]),
+            task("c", &["b"]),
+        ])
+        .ok()
+        .unwrap();
+
+        graph.take_ready();
+
+        let skipped = graph.fail("a");
+
+        assert_eq!(skipped, vec!["b", "c"]);
+        assert!(graph.is_done());
+    }
+
+    #[test]
+    fn build_rejects_duplicate_names() {
+        let err = Graph::build(vec![task("a", &[]), task("a", &[])])
+            .err()
+            .unwrap();
+
+        assert!(matches!(err, BuildError::DuplicateName(name) if name == "a"));
+    }
+
+    #[test]
+    fn build_rejects_unknown_dependency() {
+        let err = Graph::build(vec![task("a", &["missing"])]).err().unwrap();
+
+        assert!(matches!(
+            err,
+            BuildError::UnknownDependency { task, dependency }
+                if task == "a" && dependency == "missing"
+        ));
+    }
+
+    #[test]
+    fn build_rejects_cycle() {
+        let err = Graph::build(vec![task("a", &["b"]), task("b", &["a"])])
+            .err()
+            .unwrap();
+
+        assert!(matches!(err, BuildError::Cycle(cyclic) if {
+            let mut cyclic = cyclic.clone();
+            cyclic.sort();
+            cyclic == vec!["a", "b"]
+        }));
+    }
+}
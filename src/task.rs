@@ -1,38 +1,82 @@
+use crate::cache::Cache;
+use crate::error::TdistError;
+use crate::jobserver::JobServer;
+use crate::sandbox::{self, SandboxConfig};
 use crate::task_file::{TaskFile, TaskFileCommand};
+use crate::template;
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicI32, Ordering};
 use tracing::{info, warn};
 
 pub struct Task {
     pub id: i32,
     pub name: String,
+    pub depends_on: Vec<String>,
     repeat: usize,
     commands: Vec<Command>,
 }
 
 impl Task {
-    pub fn from_task_file(task_file: TaskFile, task_id: &AtomicI32) -> Self {
-        Self {
-            id: task_id.fetch_add(1, Ordering::SeqCst),
-            name: task_file.name,
+    pub fn from_task_file(
+        task_file: TaskFile,
+        task_id: &AtomicI32,
+        variables: &HashMap<String, String>,
+    ) -> Result<Self, TdistError> {
+        let id = task_id.fetch_add(1, Ordering::SeqCst);
+        let name = task_file.name;
+
+        let commands = task_file
+            .commands
+            .into_iter()
+            .map(|command| {
+                Command::from_task_file_command(command, &name, id, variables, &task_file.vars)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            id,
+            name,
+            depends_on: task_file.depends_on,
             repeat: task_file.repeat.0,
-            commands: task_file.commands.into_iter().map(Into::into).collect(),
-        }
+            commands,
+        })
     }
 
-    pub fn run(&self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn run(
+        &self,
+        jobserver: &JobServer,
+        cache: &Cache,
+        sandbox_config: &SandboxConfig,
+    ) -> Result<(), TdistError> {
+        let digest = Cache::digest(&self.commands);
+        let outputs: Vec<&PathBuf> = self.commands.iter().flat_map(Command::outputs).collect();
+
+        if cache.is_up_to_date(&self.name, &digest, &outputs) {
+            info!("Task {} is up to date, skipping", self.name);
+
+            return Ok(());
+        }
+
         let mut repeat = self.repeat;
         let is_infinite = repeat == 0;
 
         while is_infinite || repeat > 0 {
-            self.run_commands()?;
+            self.run_commands(jobserver, sandbox_config)?;
 
             repeat = repeat.saturating_sub(1);
         }
 
+        cache.store(&self.name, &digest)?;
+
         Ok(())
     }
 
-    fn run_commands(&self) -> Result<(), Box<dyn std::error::Error>> {
+    fn run_commands(
+        &self,
+        jobserver: &JobServer,
+        sandbox_config: &SandboxConfig,
+    ) -> Result<(), TdistError> {
         let mut handles = Vec::new();
 
         for command in &self.commands {
@@ -41,6 +85,9 @@ impl Task {
                     command,
                     ignore_failure,
                     parallel,
+                    inputs,
+                    sandbox: sandboxed,
+                    ..
                 } => {
                     if *parallel {
                         info!("Running command in parallel: {}", command);
@@ -48,13 +95,17 @@ impl Task {
                         let handle = std::thread::spawn({
                             let command = command.clone();
                             let ignore_failure = *ignore_failure;
+                            let jobserver = jobserver.clone();
+                            let task_id = self.id;
+                            let inputs = inputs.clone();
+                            let sandboxed = *sandboxed;
+                            let sandbox_config = sandbox_config.clone();
+
+                            move || -> Result<(), TdistError> {
+                                let _token = jobserver.acquire();
 
-                            move || {
-                                let status = std::process::Command::new("sh")
-                                    .arg("-c")
-                                    .arg(&command)
-                                    .status()
-                                    .map_err(|err| err.to_string())?;
+                                let status =
+                                    sandbox::run(&command, sandboxed, &sandbox_config, &inputs)?;
 
                                 info!("Finished running command: {}", command);
 
@@ -63,7 +114,11 @@ impl Task {
 
                                     Ok(())
                                 } else if !status.success() {
-                                    Err(format!("Command `{}` failed: {}", command, status))
+                                    Err(TdistError::CommandExecution {
+                                        task_id,
+                                        command,
+                                        message: status.to_string(),
+                                    })
                                 } else {
                                     Ok(())
                                 }
@@ -74,17 +129,18 @@ impl Task {
                     } else {
                         info!("Running command: {}", command);
 
-                        let status = std::process::Command::new("sh")
-                            .arg("-c")
-                            .arg(command)
-                            .status()?;
+                        let status = sandbox::run(command, *sandboxed, sandbox_config, inputs)?;
 
                         info!("Finished running command: {}", command);
 
                         if *ignore_failure {
                             warn!("Ignoring failure for command: {}", command);
                         } else if !status.success() {
-                            return Err(format!("Command `{}` failed: {}", command, status).into());
+                            return Err(TdistError::CommandExecution {
+                                task_id: self.id,
+                                command: command.clone(),
+                                message: status.to_string(),
+                            });
                         }
 
                         for handle in handles.drain(..) {
@@ -108,19 +164,70 @@ pub enum Command {
         command: String,
         ignore_failure: bool,
         parallel: bool,
+        inputs: Vec<PathBuf>,
+        outputs: Vec<PathBuf>,
+        sandbox: bool,
     },
 }
 
-impl From<TaskFileCommand> for Command {
-    fn from(taskfile_command: TaskFileCommand) -> Self {
-        if let Some(command) = taskfile_command.shell {
-            Self::Shell {
-                command,
-                ignore_failure: taskfile_command.ignore_failure,
-                parallel: taskfile_command.parallel,
-            }
-        } else {
-            panic!()
-        }
+impl Command {
+    pub(crate) fn shell(&self) -> &str {
+        let Self::Shell { command, .. } = self;
+
+        command
+    }
+
+    pub(crate) fn inputs(&self) -> &[PathBuf] {
+        let Self::Shell { inputs, .. } = self;
+
+        inputs
+    }
+
+    pub(crate) fn outputs(&self) -> &[PathBuf] {
+        let Self::Shell { outputs, .. } = self;
+
+        outputs
+    }
+
+    pub(crate) fn ignore_failure(&self) -> bool {
+        let Self::Shell { ignore_failure, .. } = self;
+
+        *ignore_failure
+    }
+
+    pub(crate) fn sandbox(&self) -> bool {
+        let Self::Shell { sandbox, .. } = self;
+
+        *sandbox
+    }
+
+    fn from_task_file_command(
+        taskfile_command: TaskFileCommand,
+        task_name: &str,
+        task_id: i32,
+        variables: &HashMap<String, String>,
+        vars: &HashMap<String, String>,
+    ) -> Result<Self, TdistError> {
+        let Some(shell) = taskfile_command.shell else {
+            return Err(TdistError::InvalidCommand {
+                message: format!("task '{}' has a command with no `shell`", task_name),
+            });
+        };
+
+        let command =
+            template::render(&shell, task_name, task_id, variables, vars).map_err(|err| {
+                TdistError::InvalidCommand {
+                    message: err.to_string(),
+                }
+            })?;
+
+        Ok(Self::Shell {
+            command,
+            ignore_failure: taskfile_command.ignore_failure,
+            parallel: taskfile_command.parallel,
+            inputs: taskfile_command.inputs,
+            outputs: taskfile_command.outputs,
+            sandbox: taskfile_command.sandbox,
+        })
     }
 }
@@ -1,10 +1,56 @@
+use crate::error::TdistError;
+use glob::Pattern;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 
-pub fn get_task_files<P: AsRef<Path>>(task_dir: P) -> std::io::Result<Vec<PathBuf>> {
-    std::fs::read_dir(task_dir)?
-        .map(|entry| entry.map(|entry| entry.path()))
-        .collect::<Result<Vec<_>, _>>()
+/// Recursively discovers task files under `task_dir`, matching each pattern
+/// in `include` (relative to `task_dir`) and dropping any result that also
+/// matches a pattern in `exclude`.
+pub fn get_task_files<P: AsRef<Path>>(
+    task_dir: P,
+    include: &[String],
+    exclude: &[String],
+) -> Result<Vec<PathBuf>, TdistError> {
+    let task_dir = task_dir.as_ref();
+
+    let exclude_patterns: Vec<Pattern> = exclude
+        .iter()
+        .map(|pattern| Pattern::new(pattern).map_err(|err| TdistError::other(err.to_string())))
+        .collect::<Result<_, _>>()?;
+
+    let mut files = Vec::new();
+    let mut seen = HashSet::new();
+
+    for pattern in include {
+        let full_pattern = task_dir.join(pattern);
+
+        let entries = glob::glob(&full_pattern.to_string_lossy())
+            .map_err(|err| TdistError::other(err.to_string()))?;
+
+        for entry in entries {
+            let path = entry.map_err(|err| TdistError::other(err.to_string()))?;
+
+            if !path.is_file() {
+                continue;
+            }
+
+            let relative = path.strip_prefix(task_dir).unwrap_or(&path);
+
+            if exclude_patterns
+                .iter()
+                .any(|pattern| pattern.matches_path(relative))
+            {
+                continue;
+            }
+
+            if seen.insert(path.clone()) {
+                files.push(path);
+            }
+        }
+    }
+
+    Ok(files)
 }
 
 #[derive(Serialize, Deserialize)]
@@ -12,6 +58,14 @@ pub struct TaskFile {
     pub name: String,
     #[serde(default)]
     pub repeat: Repeat,
+    /// Names of other task files that must finish successfully before this
+    /// one is scheduled.
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Variables available to this task file's command templates, on top
+    /// of the config's global `[variables]` table.
+    #[serde(default)]
+    pub vars: HashMap<String, String>,
     #[serde(rename = "command")]
     pub commands: Vec<TaskFileCommand>,
 }
@@ -34,4 +88,20 @@ pub struct TaskFileCommand {
 
     #[serde(default)]
     pub parallel: bool,
+
+    /// Files this command reads. Included in the task's cache digest so
+    /// edits to any of them invalidate the cache.
+    #[serde(default)]
+    pub inputs: Vec<PathBuf>,
+
+    /// Files this command produces. Checked for existence before a cached
+    /// task is skipped.
+    #[serde(default)]
+    pub outputs: Vec<PathBuf>,
+
+    /// When true, and the config's `[sandbox]` section is enabled, this
+    /// command runs inside an isolated mount/PID namespace instead of
+    /// directly via `sh -c`.
+    #[serde(default)]
+    pub sandbox: bool,
 }
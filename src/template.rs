@@ -0,0 +1,39 @@
+use handlebars::Handlebars;
+use std::collections::HashMap;
+
+/// Renders a command's `shell` template against the process environment,
+/// the config's global `[variables]` table, a task file's own `[vars]`
+/// table, and the task's own `name`/`id`, so task files can stay reusable
+/// across environments instead of hard-coding paths.
+pub fn render(
+    template: &str,
+    name: &str,
+    id: i32,
+    variables: &HashMap<String, String>,
+    vars: &HashMap<String, String>,
+) -> Result<String, handlebars::RenderError> {
+    let mut handlebars = Handlebars::new();
+    handlebars.register_escape_fn(handlebars::no_escape);
+    // Without strict mode, a typo'd variable silently renders to an empty
+    // string instead of surfacing as an error.
+    handlebars.set_strict_mode(true);
+
+    let mut context = HashMap::new();
+
+    for (key, value) in std::env::vars() {
+        context.insert(key, value);
+    }
+
+    for (key, value) in variables {
+        context.insert(key.clone(), value.clone());
+    }
+
+    for (key, value) in vars {
+        context.insert(key.clone(), value.clone());
+    }
+
+    context.insert(String::from("name"), name.to_owned());
+    context.insert(String::from("id"), id.to_string());
+
+    handlebars.render_template(template, &context)
+}